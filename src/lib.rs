@@ -1,7 +1,11 @@
 mod min_heap;
+mod shuffle;
+pub mod validator_mandates;
 
 use min_heap::MinHeap;
+use shuffle::swap_or_not_shuffle;
 use std::cmp;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 
 pub type Balance = u128;
 pub type ShardId = usize;
@@ -27,20 +31,83 @@ impl ValidatorStake {
 /// stake assigned to each shard approximately equal). This function performs
 /// best when the number of block producers is greater than
 /// `num_shards * min_validators_per_shard`.
+///
+/// `prev_remained` carries, for each shard (indexed by `ShardId`), the
+/// `AccountId`s that were assigned to that shard in the previous epoch.
+/// Any of those accounts still present in `block_producers` are re-placed
+/// onto the same shard before the rest of the assignment runs, since shards
+/// carry state and moving a validator to a new shard forces it to
+/// re-download and track a new trie. Pass an empty slice to opt out of
+/// sticky assignment entirely.
+///
+/// If `seed` is provided, `block_producers` is first permuted with the
+/// swap-or-not shuffle (see `shuffle::swap_or_not_shuffle`), preventing a
+/// validator from biasing its shard by controlling registration order.
+/// Pass `None` to keep the current, unshuffled behaviour.
 pub fn assign_shards(
     block_producers: Vec<ValidatorStake>,
     num_shards: usize,
     min_validators_per_shard: usize,
+    prev_remained: &[Vec<AccountId>],
+    seed: Option<[u8; 32]>,
 ) -> Vec<Vec<ValidatorStake>> {
-    // Initially, sort by number of validators, then total stake
-    // (i.e. favour filling under-occupied shards first).
-    let mut shard_index: MinHeap<(usize, Balance, ShardId)> =
-        (0..num_shards).map(|s| (0, 0, s)).collect();
+    let block_producers = match seed {
+        Some(seed) => swap_or_not_shuffle(block_producers, &seed),
+        None => block_producers,
+    };
 
     let num_block_producers = block_producers.len();
     if num_block_producers < min_validators_per_shard {
         panic!("Not enough block producers to minimally fill shards");
     }
+
+    let mut result: Vec<Vec<ValidatorStake>> = (0..num_shards).map(|_| Vec::new()).collect();
+    let mut initial_counts: Vec<usize> = vec![0; num_shards];
+    let mut initial_stakes: Vec<Balance> = vec![0; num_shards];
+    let mut sticky_accounts: HashSet<AccountId> = HashSet::new();
+
+    // Re-place validators who are sticky to a shard from the previous epoch
+    // and are still block producers. Producers that no longer exist are
+    // simply skipped.
+    for (shard_id, account_ids) in prev_remained.iter().enumerate().take(num_shards) {
+        for account_id in account_ids {
+            if let Some(bp) = block_producers
+                .iter()
+                .find(|bp| &bp.account_id == account_id)
+            {
+                initial_counts[shard_id] += 1;
+                initial_stakes[shard_id] += bp.stake;
+                result[shard_id].push(bp.clone());
+                sticky_accounts.insert(bp.account_id.clone());
+            }
+        }
+    }
+
+    // Only producers not already placed via the sticky pre-fill above are
+    // eligible for the rest of the assignment, so a sticky validator can
+    // never end up duplicated onto a second shard.
+    let block_producers: Vec<ValidatorStake> = block_producers
+        .into_iter()
+        .filter(|bp| !sticky_accounts.contains(&bp.account_id))
+        .collect();
+
+    // A lopsided `prev_remained` can consume so many producers into sticky
+    // placements that too few are left to bring the other shards up to
+    // `min_validators_per_shard`. Catch that up front with a clear message
+    // rather than letting `bp_iter` run dry partway through the fill below.
+    if initial_counts.iter().any(|&count| count < min_validators_per_shard)
+        && block_producers.len() < min_validators_per_shard
+    {
+        panic!("Not enough non-sticky block producers left to minimally fill the remaining shards");
+    }
+
+    // Initially, sort by number of validators, then total stake
+    // (i.e. favour filling under-occupied shards first), seeded with
+    // whatever sticky assignments were made above.
+    let mut shard_index: MinHeap<(usize, Balance, ShardId)> = (0..num_shards)
+        .map(|s| (initial_counts[s], initial_stakes[s], s))
+        .collect();
+    let num_block_producers = block_producers.len();
     let required_validator_count =
         cmp::max(num_block_producers, num_shards * min_validators_per_shard);
     let mut bp_iter = block_producers
@@ -49,8 +116,6 @@ pub fn assign_shards(
         .enumerate()
         .take(required_validator_count);
 
-    let mut result: Vec<Vec<ValidatorStake>> = (0..num_shards).map(|_| Vec::new()).collect();
-
     // Place validators into shards while there are still some without the
     // minimum required number.
     while shard_index.peek().unwrap().0 < min_validators_per_shard {
@@ -122,12 +187,211 @@ pub fn assign_shards(
         }
     }
 
+    refine_stake_balance(&mut result, min_validators_per_shard);
+
     result
 }
 
+/// Upper bound on how many move-or-swap iterations `refine_stake_balance`
+/// will attempt before giving up, so a pathological input can't loop forever.
+const MAX_REFINEMENT_ITERATIONS: usize = 1000;
+
+/// Post-processing pass that runs after the initial two-phase fill to shrink
+/// the gap between the richest and poorest shard by stake. Each iteration
+/// looks at the current richest and poorest shards and tries a pure
+/// validator move first; if no move helps, it falls back to a pairwise swap.
+/// Stops as soon as no further improving move exists or the iteration cap is
+/// hit.
+fn refine_stake_balance(result: &mut [Vec<ValidatorStake>], min_validators_per_shard: usize) {
+    if result.len() < 2 {
+        return;
+    }
+
+    for _ in 0..MAX_REFINEMENT_ITERATIONS {
+        let stakes: Vec<Balance> = result
+            .iter()
+            .map(|shard| shard.iter().map(|bp| bp.stake).sum())
+            .collect();
+
+        let mut richest: BinaryHeap<(Balance, ShardId)> = stakes
+            .iter()
+            .enumerate()
+            .map(|(shard_id, &stake)| (stake, shard_id))
+            .collect();
+        let mut poorest: MinHeap<(Balance, ShardId)> = stakes
+            .into_iter()
+            .enumerate()
+            .map(|(shard_id, stake)| (stake, shard_id))
+            .collect();
+
+        let (rich_stake, rich_shard) = richest.pop().expect("richest should never be empty");
+        let (poor_stake, poor_shard) = poorest.pop().expect("poorest should never be empty");
+
+        if rich_shard == poor_shard
+            || !try_improve_stake_balance(
+                result,
+                rich_shard,
+                poor_shard,
+                rich_stake,
+                poor_stake,
+                min_validators_per_shard,
+            )
+        {
+            break;
+        }
+    }
+}
+
+/// Tries to shrink the stake gap between `rich_shard` and `poor_shard` by
+/// either moving a single validator from the rich shard to the poor one, or
+/// (if no move helps) swapping a higher-stake validator on the rich shard for
+/// a lower-stake one on the poor shard. Returns whether an improving move was
+/// made.
+fn try_improve_stake_balance(
+    result: &mut [Vec<ValidatorStake>],
+    rich_shard: ShardId,
+    poor_shard: ShardId,
+    rich_stake: Balance,
+    poor_stake: Balance,
+    min_validators_per_shard: usize,
+) -> bool {
+    let gap = rich_stake - poor_stake;
+
+    if result[rich_shard].len() > min_validators_per_shard {
+        let best_move = result[rich_shard]
+            .iter()
+            .enumerate()
+            .filter(|(_, bp)| !result[poor_shard].contains(bp))
+            .map(|(i, bp)| {
+                let new_gap = (rich_stake - bp.stake).abs_diff(poor_stake + bp.stake);
+                (i, new_gap)
+            })
+            .filter(|&(_, new_gap)| new_gap < gap)
+            .min_by_key(|&(_, new_gap)| new_gap);
+
+        if let Some((i, _)) = best_move {
+            let validator = result[rich_shard].remove(i);
+            result[poor_shard].push(validator);
+            return true;
+        }
+    }
+
+    let best_swap = result[rich_shard]
+        .iter()
+        .enumerate()
+        .flat_map(|(ri, rich_bp)| {
+            result[poor_shard]
+                .iter()
+                .enumerate()
+                .map(move |(pi, poor_bp)| (ri, pi, rich_bp, poor_bp))
+        })
+        .filter(|(_, _, rich_bp, poor_bp)| rich_bp.stake > poor_bp.stake)
+        .filter(|(_, _, rich_bp, poor_bp)| {
+            !result[poor_shard].contains(rich_bp) && !result[rich_shard].contains(poor_bp)
+        })
+        .map(|(ri, pi, rich_bp, poor_bp)| {
+            let new_gap = (rich_stake - rich_bp.stake + poor_bp.stake)
+                .abs_diff(poor_stake - poor_bp.stake + rich_bp.stake);
+            (ri, pi, new_gap)
+        })
+        .filter(|&(_, _, new_gap)| new_gap < gap)
+        .min_by_key(|&(_, _, new_gap)| new_gap);
+
+    if let Some((ri, pi, _)) = best_swap {
+        let rich_bp = result[rich_shard].remove(ri);
+        let poor_bp = result[poor_shard].remove(pi);
+        result[rich_shard].push(poor_bp);
+        result[poor_shard].push(rich_bp);
+        return true;
+    }
+
+    false
+}
+
+/// Gap between the most- and least-staked shard in `assignment`. Exposed so
+/// callers can assert how tight the stake balance `assign_shards` actually
+/// achieved, rather than recomputing per-shard totals themselves.
+pub fn max_min_stake_imbalance(assignment: &[Vec<ValidatorStake>]) -> Balance {
+    let totals: Vec<Balance> = assignment
+        .iter()
+        .map(|shard| shard.iter().map(|bp| bp.stake).sum())
+        .collect();
+    let max = totals.iter().copied().max().unwrap_or(0);
+    let min = totals.iter().copied().min().unwrap_or(0);
+    max - min
+}
+
+/// Reverse-indexed view of a shard assignment, built by [`assign_shards_indexed`].
+/// Wraps the same `Vec<Vec<ValidatorStake>>` that [`assign_shards`] returns,
+/// precomputing a validator-to-shards reverse index and per-shard stake
+/// totals so callers don't have to rescan the nested vectors themselves.
+pub struct ShardAssignment {
+    assignment: Vec<Vec<ValidatorStake>>,
+    reverse_index: HashMap<AccountId, Vec<ShardId>>,
+    stakes: Vec<Balance>,
+}
+
+impl ShardAssignment {
+    fn new(assignment: Vec<Vec<ValidatorStake>>) -> Self {
+        let mut reverse_index: HashMap<AccountId, Vec<ShardId>> = HashMap::new();
+        let mut stakes = vec![0; assignment.len()];
+        for (shard_id, bps) in assignment.iter().enumerate() {
+            for bp in bps {
+                reverse_index
+                    .entry(bp.account_id.clone())
+                    .or_default()
+                    .push(shard_id);
+                stakes[shard_id] += bp.stake;
+            }
+        }
+        Self {
+            assignment,
+            reverse_index,
+            stakes,
+        }
+    }
+
+    /// Shards `account` is assigned to. Empty if `account` is not a block
+    /// producer in this assignment.
+    pub fn shards_for(&self, account: &AccountId) -> &[ShardId] {
+        self.reverse_index
+            .get(account)
+            .map(|shards| shards.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Validators assigned to `shard`.
+    pub fn validators_on(&self, shard: ShardId) -> &[ValidatorStake] {
+        &self.assignment[shard]
+    }
+
+    /// Total stake assigned to `shard`.
+    pub fn stake_on(&self, shard: ShardId) -> Balance {
+        self.stakes[shard]
+    }
+}
+
+/// Same as [`assign_shards`], but wraps the result in a [`ShardAssignment`].
+pub fn assign_shards_indexed(
+    block_producers: Vec<ValidatorStake>,
+    num_shards: usize,
+    min_validators_per_shard: usize,
+    prev_remained: &[Vec<AccountId>],
+    seed: Option<[u8; 32]>,
+) -> ShardAssignment {
+    let assignment = assign_shards(
+        block_producers,
+        num_shards,
+        min_validators_per_shard,
+        prev_remained,
+        seed,
+    );
+    ShardAssignment::new(assignment)
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{assign_shards, ValidatorStake};
+    use super::{assign_shards, assign_shards_indexed, max_min_stake_imbalance, ValidatorStake};
     use std::cmp;
     use std::collections::HashSet;
 
@@ -147,8 +411,20 @@ mod tests {
 
     #[test]
     fn test_exponential_distribution_many_shards() {
-        // algorithm performs even worse when there are many shards
-        test_exponential_distribution_common(24, 41);
+        // the stake-rebalancing refinement pass tightens this considerably
+        // compared to the initial fill alone
+        test_exponential_distribution_common(24, 25);
+    }
+
+    #[test]
+    fn test_refinement_tightens_many_shards_imbalance() {
+        let stakes = &EXPONENTIAL_STAKES;
+        let block_producers = make_validators(stakes);
+        let assignment = assign_shards(block_producers, 24, 2, &[], None);
+
+        // the unrefined greedy fill alone leaves an imbalance around 75 for
+        // this input; the refinement pass should shrink that substantially
+        assert!(max_min_stake_imbalance(&assignment) < 50);
     }
 
     #[test]
@@ -158,7 +434,7 @@ mod tests {
         let block_producers = make_validators(stakes);
         let num_shards = 1;
         let min_validators_per_shard = 3; // one validator cannot fill 3 slots
-        assign_shards(block_producers, num_shards, min_validators_per_shard);
+        assign_shards(block_producers, num_shards, min_validators_per_shard, &[], None);
     }
 
     #[test]
@@ -168,7 +444,7 @@ mod tests {
         let block_producers = make_validators(stakes);
         let min_validators_per_shard = 2;
 
-        let assignment = assign_shards(block_producers, num_shards, min_validators_per_shard);
+        let assignment = assign_shards(block_producers, num_shards, min_validators_per_shard, &[], None);
 
         // The algorithm ensures the minimum number of validators is present
         // in each shard, even if it makes the stakes more uneven.
@@ -183,6 +459,141 @@ mod tests {
         assert_eq!(stake_1, 90);
     }
 
+    #[test]
+    fn test_sticky_assignment() {
+        let num_shards = 3;
+        let stakes = &[100, 90, 81, 73, 66, 59, 53, 48, 43];
+        let block_producers = make_validators(stakes);
+        let min_validators_per_shard = 1;
+
+        // `C` was on shard 1 last epoch and is still a block producer, so it
+        // should be re-placed there even though it would otherwise be greedily
+        // assigned to a different shard. `Z` no longer exists, so it is skipped.
+        let prev_remained = vec![
+            Vec::new(),
+            vec!["C".to_string(), "Z".to_string()],
+            Vec::new(),
+        ];
+
+        let assignment = assign_shards(
+            block_producers,
+            num_shards,
+            min_validators_per_shard,
+            &prev_remained,
+            None,
+        );
+
+        assert!(assignment[1]
+            .iter()
+            .any(|bp| bp.account_id == "C"));
+
+        // no validator should be assigned to more than one shard here, since
+        // `min_validators_per_shard` is already satisfied without reusing
+        // any of them
+        let mut seen = HashSet::new();
+        for bps in &assignment {
+            for bp in bps {
+                assert!(
+                    seen.insert(&bp.account_id),
+                    "{} was assigned to more than one shard",
+                    bp.account_id
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Not enough non-sticky block producers")]
+    fn test_lopsided_sticky_distribution_panics_clearly() {
+        // All 4 validators were on shard 0 last epoch, leaving none free to
+        // bring shard 1 up to its minimum of 2.
+        let stakes = &[100, 90, 81, 73];
+        let block_producers = make_validators(stakes);
+        let num_shards = 2;
+        let min_validators_per_shard = 2;
+        let prev_remained = vec![
+            vec![
+                "A".to_string(),
+                "B".to_string(),
+                "C".to_string(),
+                "D".to_string(),
+            ],
+            Vec::new(),
+        ];
+
+        assign_shards(
+            block_producers,
+            num_shards,
+            min_validators_per_shard,
+            &prev_remained,
+            None,
+        );
+    }
+
+    #[test]
+    fn test_seeded_assignment_is_deterministic() {
+        let stakes = &EXPONENTIAL_STAKES;
+        let num_shards = 4;
+        let min_validators_per_shard = 2;
+        let seed = [5u8; 32];
+
+        let assignment_a = assign_shards(
+            make_validators(stakes),
+            num_shards,
+            min_validators_per_shard,
+            &[],
+            Some(seed),
+        );
+        let assignment_b = assign_shards(
+            make_validators(stakes),
+            num_shards,
+            min_validators_per_shard,
+            &[],
+            Some(seed),
+        );
+
+        assert_eq!(assignment_a, assignment_b);
+    }
+
+    #[test]
+    fn test_shard_assignment_indexed() {
+        let num_shards = 3;
+        let stakes = &[100, 90, 81, 73, 66, 59, 53, 48, 43];
+        let block_producers = make_validators(stakes);
+        let min_validators_per_shard = 1;
+
+        let assignment = assign_shards_indexed(
+            block_producers,
+            num_shards,
+            min_validators_per_shard,
+            &[],
+            None,
+        );
+
+        // every validator's reverse-indexed shards should match where it
+        // actually shows up in validators_on
+        for shard in 0..num_shards {
+            for bp in assignment.validators_on(shard) {
+                assert!(assignment
+                    .shards_for(&bp.account_id)
+                    .contains(&shard));
+            }
+        }
+
+        // stake_on should match the sum of validators_on's stakes
+        for shard in 0..num_shards {
+            let expected: u128 = assignment
+                .validators_on(shard)
+                .iter()
+                .map(|bp| bp.stake)
+                .sum();
+            assert_eq!(assignment.stake_on(shard), expected);
+        }
+
+        // an account that was never a block producer has no shards
+        assert!(assignment.shards_for(&"nobody".to_string()).is_empty());
+    }
+
     fn test_exponential_distribution_common(num_shards: usize, diff_tolerance: i128) {
         let stakes = &EXPONENTIAL_STAKES;
         let block_producers = make_validators(stakes);
@@ -192,7 +603,7 @@ mod tests {
             cmp::max(block_producers.len() / num_shards, min_validators_per_shard);
         let average_stake_per_shard =
             (validators_per_shard as u128) * stakes.iter().sum::<u128>() / (stakes.len() as u128);
-        let assignment = assign_shards(block_producers, num_shards, min_validators_per_shard);
+        let assignment = assign_shards(block_producers, num_shards, min_validators_per_shard, &[], None);
 
         // validator distribution should be even
         assert!(assignment
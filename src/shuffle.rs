@@ -0,0 +1,105 @@
+use sha2::{Digest, Sha256};
+use std::cmp;
+use std::convert::TryInto;
+
+/// Number of rounds the swap-or-not permutation runs for. 90 rounds gives a
+/// negligible bias even for large `index_count`, matching the round count
+/// used for validator shuffling in beacon-chain designs.
+const ROUNDS: u8 = 90;
+
+fn hash(input: &[u8]) -> [u8; 32] {
+    let digest = Sha256::digest(input);
+    let mut out = [0u8; 32];
+    out.copy_from_slice(&digest);
+    out
+}
+
+/// Runs the swap-or-not permutation for a single index, returning where that
+/// index ends up after all rounds. Calling this for every `0..index_count`
+/// yields the full permutation computed by `swap_or_not_shuffle`.
+fn compute_shuffled_index(mut index: usize, index_count: usize, seed: &[u8; 32]) -> usize {
+    assert!(index < index_count, "index out of bounds");
+
+    for round in 0..ROUNDS {
+        let mut pivot_input = seed.to_vec();
+        pivot_input.push(round);
+        let pivot_hash = hash(&pivot_input);
+        let pivot =
+            (u64::from_le_bytes(pivot_hash[0..8].try_into().unwrap()) as usize) % index_count;
+
+        let flip = (pivot + index_count - index) % index_count;
+        let position = cmp::max(index, flip);
+
+        let mut source_input = seed.to_vec();
+        source_input.push(round);
+        source_input.extend_from_slice(&((position / 256) as u32).to_le_bytes());
+        let source = hash(&source_input);
+
+        let byte = source[(position % 256) / 8];
+        let bit = (byte >> (position % 8)) & 1;
+        if bit == 1 {
+            index = flip;
+        }
+    }
+
+    index
+}
+
+/// Permutes `items` using the seeded swap-or-not shuffle. The same `seed`
+/// always produces the same permutation, so the result is reproducible by any
+/// verifier who knows it, but unpredictable to anyone who doesn't.
+pub fn swap_or_not_shuffle<T: Clone>(items: Vec<T>, seed: &[u8; 32]) -> Vec<T> {
+    let n = items.len();
+    if n <= 1 {
+        return items;
+    }
+
+    (0..n)
+        .map(|i| items[compute_shuffled_index(i, n, seed)].clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::swap_or_not_shuffle;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_shuffle_is_a_permutation() {
+        let items: Vec<usize> = (0..20).collect();
+        let shuffled = swap_or_not_shuffle(items.clone(), &[3u8; 32]);
+
+        assert_eq!(shuffled.len(), items.len());
+        assert_eq!(
+            shuffled.iter().copied().collect::<HashSet<_>>(),
+            items.into_iter().collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_shuffle_is_deterministic() {
+        let items: Vec<usize> = (0..20).collect();
+        let seed = [9u8; 32];
+
+        let shuffled_a = swap_or_not_shuffle(items.clone(), &seed);
+        let shuffled_b = swap_or_not_shuffle(items, &seed);
+
+        assert_eq!(shuffled_a, shuffled_b);
+    }
+
+    #[test]
+    fn test_different_seeds_differ() {
+        let items: Vec<usize> = (0..20).collect();
+
+        let shuffled_a = swap_or_not_shuffle(items.clone(), &[1u8; 32]);
+        let shuffled_b = swap_or_not_shuffle(items, &[2u8; 32]);
+
+        assert_ne!(shuffled_a, shuffled_b);
+    }
+
+    #[test]
+    fn test_empty_and_singleton_are_noops() {
+        assert_eq!(swap_or_not_shuffle(Vec::<usize>::new(), &[0u8; 32]), Vec::<usize>::new());
+        assert_eq!(swap_or_not_shuffle(vec![7], &[0u8; 32]), vec![7]);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::min_heap::MinHeap;
+use crate::{Balance, ShardId, ValidatorStake};
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+/// A portion of a validator's stake left over after carving out whole
+/// mandates worth exactly `stake_per_mandate`. Unlike whole mandates, which
+/// are all interchangeable, a partial mandate's `weight` varies validator to
+/// validator, so it is sampled rather than simply shuffled.
+#[derive(Debug, Clone)]
+struct PartialMandate {
+    validator: ValidatorStake,
+    weight: Balance,
+}
+
+/// Stake-weighted alternative to [`crate::assign_shards`]. Rather than
+/// greedily packing validators onto shards, each validator's stake is broken
+/// into `floor(stake / stake_per_mandate)` whole mandates plus (if there is a
+/// remainder) a single partial mandate. Whole mandates are shuffled with a
+/// seeded RNG and dealt out round-robin so every shard gets approximately the
+/// same number of them; partial mandates are then distributed by weighted
+/// sampling to even out whatever residual stake remains. Because mandates
+/// (not validators) are what gets assigned, a single heavily-staked validator
+/// can legitimately end up in the output for more than one shard.
+///
+/// The `seed` is the only source of randomness, so the same inputs always
+/// produce the same assignment and any verifier can reproduce it.
+///
+/// Unlike `assign_shards`, this does not enforce a minimum validator count
+/// per shard; it trades that guarantee for stake balance that holds up even
+/// when `num_shards` is large.
+pub fn assign_mandates(
+    block_producers: Vec<ValidatorStake>,
+    stake_per_mandate: Balance,
+    num_shards: usize,
+    seed: [u8; 32],
+) -> Vec<Vec<ValidatorStake>> {
+    assert!(stake_per_mandate > 0, "stake_per_mandate must be positive");
+
+    let mut rng = ChaCha20Rng::from_seed(seed);
+
+    let mut whole_mandates: Vec<ValidatorStake> = Vec::new();
+    let mut partial_mandates: Vec<PartialMandate> = Vec::new();
+    for bp in &block_producers {
+        let whole_mandate_count = bp.stake / stake_per_mandate;
+        for _ in 0..whole_mandate_count {
+            whole_mandates.push(bp.clone());
+        }
+        let remainder = bp.stake % stake_per_mandate;
+        if remainder > 0 {
+            partial_mandates.push(PartialMandate {
+                validator: bp.clone(),
+                weight: remainder,
+            });
+        }
+    }
+    whole_mandates.shuffle(&mut rng);
+
+    let mut result: Vec<Vec<ValidatorStake>> = (0..num_shards).map(|_| Vec::new()).collect();
+
+    // Deal whole mandates round-robin via the same min-count heap
+    // `assign_shards` uses, so every shard gets the same number of them
+    // (give or take one).
+    let mut count_heap: MinHeap<(usize, ShardId)> = (0..num_shards).map(|s| (0, s)).collect();
+    let mut shard_stakes: Vec<Balance> = vec![0; num_shards];
+    for validator in whole_mandates {
+        let (count, shard_id) = count_heap.pop().expect("count_heap should never be empty");
+        count_heap.push((count + 1, shard_id));
+        shard_stakes[shard_id] += stake_per_mandate;
+        result[shard_id].push(validator);
+    }
+
+    // Distribute partial mandates by weighted sampling, always handing the
+    // sampled mandate to whichever shard currently holds the least stake.
+    let mut stake_heap: MinHeap<(Balance, ShardId)> = shard_stakes
+        .into_iter()
+        .enumerate()
+        .map(|(shard_id, stake)| (stake, shard_id))
+        .collect();
+    let mut remaining = partial_mandates;
+    while !remaining.is_empty() {
+        let weights: Vec<f64> = remaining.iter().map(|m| m.weight as f64).collect();
+        let dist =
+            WeightedIndex::new(&weights).expect("partial mandate weights should be positive");
+        let index = dist.sample(&mut rng);
+        let mandate = remaining.remove(index);
+
+        let (stake, shard_id) = stake_heap.pop().expect("stake_heap should never be empty");
+        stake_heap.push((stake + mandate.weight, shard_id));
+        result[shard_id].push(mandate.validator);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assign_mandates;
+    use crate::ValidatorStake;
+
+    #[test]
+    #[should_panic(expected = "stake_per_mandate must be positive")]
+    fn test_zero_stake_per_mandate_panics() {
+        let block_producers = vec![ValidatorStake::new("a".to_string(), 100)];
+        assign_mandates(block_producers, 0, 4, [0u8; 32]);
+    }
+
+    #[test]
+    fn test_whole_mandates_spread_evenly() {
+        // Every validator here is worth exactly 2 whole mandates and has no
+        // remainder, so each shard should end up with the same mandate count.
+        let stakes = &[20, 20, 20, 20, 20, 20];
+        let block_producers: Vec<ValidatorStake> = stakes
+            .iter()
+            .enumerate()
+            .map(|(i, stake)| ValidatorStake::new(i.to_string(), *stake))
+            .collect();
+
+        let assignment = assign_mandates(block_producers, 10, 3, [7u8; 32]);
+
+        assert_eq!(assignment.iter().map(|shard| shard.len()).sum::<usize>(), 12);
+        assert!(assignment.iter().all(|shard| shard.len() == 4));
+    }
+
+    #[test]
+    fn test_heavy_validator_spans_multiple_shards() {
+        // A validator with many multiples of stake_per_mandate should show
+        // up in more than one shard's assignment.
+        let block_producers = vec![ValidatorStake::new("whale".to_string(), 100)];
+
+        let assignment = assign_mandates(block_producers, 10, 4, [1u8; 32]);
+
+        let shards_with_whale = assignment
+            .iter()
+            .filter(|shard| shard.iter().any(|bp| bp.account_id == "whale"))
+            .count();
+        assert!(shards_with_whale > 1);
+    }
+
+    #[test]
+    fn test_deterministic_given_same_seed() {
+        let stakes = &[100, 90, 81, 73, 66, 59, 53, 48, 43, 39, 35, 31];
+        let block_producers: Vec<ValidatorStake> = stakes
+            .iter()
+            .enumerate()
+            .map(|(i, stake)| ValidatorStake::new(i.to_string(), *stake))
+            .collect();
+
+        let assignment_a = assign_mandates(block_producers.clone(), 15, 24, [42u8; 32]);
+        let assignment_b = assign_mandates(block_producers, 15, 24, [42u8; 32]);
+
+        assert_eq!(assignment_a, assignment_b);
+    }
+}